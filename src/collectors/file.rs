@@ -0,0 +1,39 @@
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+use serde_json;
+
+use events;
+use payloads;
+
+/// Appends events to a file as newline-delimited JSON, one object per line.
+pub struct FileCollector {
+    file: Mutex<File>,
+}
+
+impl FileCollector {
+    pub fn new<P: AsRef<::std::path::Path>>(path: P) -> io::Result<FileCollector> {
+        let file = try!(OpenOptions::new().create(true).append(true).open(path));
+        Ok(FileCollector { file: Mutex::new(file) })
+    }
+}
+
+impl super::Collector for FileCollector {
+    fn dispatch(&self, events: &[events::Event]) -> Result<(), Box<Error>> {
+        let mut file = self.file.lock().unwrap();
+
+        for event in events {
+            let line = format!("{{\"Timestamp\":\"{}\",\"Level\":\"{}\",\"MessageTemplate\":{},\"Properties\":{}}}",
+                event.timestamp().format("%FT%TZ"),
+                event.level(),
+                serde_json::to_string(event.message_template().text()).unwrap(),
+                payloads::format_properties_json(event.properties()));
+
+            try!(writeln!(file, "{}", line));
+        }
+
+        Ok(())
+    }
+}
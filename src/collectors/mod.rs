@@ -0,0 +1,29 @@
+use std::error::Error;
+
+use events;
+
+pub mod file;
+pub mod otlp;
+pub mod seq;
+pub mod stdout;
+
+/// A destination for batches of captured events.
+///
+/// Implementations are handed already-batched events by the pipeline's
+/// background worker and are responsible for delivering them wherever they
+/// end up (an HTTP endpoint, a file, stdout, ...). `dispatch` is called from
+/// the worker thread, never from an emitting thread.
+pub trait Collector: Send + Sync {
+    fn dispatch(&self, events: &[events::Event]) -> Result<(), Box<Error>>;
+}
+
+/// Whether a `dispatch` failure is worth retrying. Defaults to `true` unless
+/// the error is a known permanent failure (e.g. Seq rejecting a batch
+/// outright), since most `Collector` implementations don't yet distinguish
+/// the two and a spurious network blip shouldn't be treated as fatal.
+pub fn is_retryable(err: &Error) -> bool {
+    match err.downcast_ref::<seq::SendError>() {
+        Some(e) => e.retryable,
+        None => true,
+    }
+}
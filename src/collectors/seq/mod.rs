@@ -2,13 +2,15 @@ use hyper;
 use hyper::header::Connection;
 use std::io::Read;
 use std::error::Error;
-use std::fmt::Write;
+use std::fmt;
 use serde_json;
 use events;
 use log;
+use payloads;
 
 pub const DEFAULT_EVENT_BODY_LIMIT_BYTES: usize = 1024 * 256;
 pub const DEFAULT_BATCH_LIMIT_BYTES: usize = 1024 * 1024 * 10;
+pub const DEFAULT_RESPONSE_BODY_LIMIT_BYTES: usize = 1024 * 64;
 pub const LOCAL_SERVER_URL: &'static str = "http://localhost:5341/";
 
 header! { (XSeqApiKey, "X-Seq-ApiKey") => [String] }
@@ -17,56 +19,92 @@ header! { (XSeqApiKey, "X-Seq-ApiKey") => [String] }
 // event with that level.
 static SEQ_LEVEL_NAMES: [&'static str; 6] = ["Fatal", "Error", "Warning", "Information", "Debug", "Verbose"];
 
+/// A non-2xx response from Seq, carrying its status and a length-capped
+/// snippet of the body. `retryable` distinguishes transient failures
+/// (429 Too Many Requests, 503 Service Unavailable) from permanent ones, so
+/// a retry layer can decide whether to back off or give up.
+#[derive(Debug)]
+pub struct SendError {
+    pub status: hyper::status::StatusCode,
+    pub retryable: bool,
+    pub body: String,
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Seq responded {} ({}): {}",
+            self.status,
+            if self.retryable { "retryable" } else { "permanent" },
+            self.body)
+    }
+}
+
+impl Error for SendError {
+    fn description(&self) -> &str {
+        "Seq rejected a batch of events"
+    }
+}
+
 pub struct SeqCollector {
-    api_key: Option<String>, 
-    event_body_limit_bytes: usize, 
+    api_key: Option<String>,
+    event_body_limit_bytes: usize,
     batch_limit_bytes: usize,
+    response_body_limit_bytes: usize,
     endpoint: String
 }
 
 impl SeqCollector {
     pub fn new<'b>(server_url: &'b str, api_key: Option<&'b str>, event_body_limit_bytes: usize, batch_limit_bytes: usize) -> SeqCollector {
+        Self::with_response_limit(server_url, api_key, event_body_limit_bytes, batch_limit_bytes, DEFAULT_RESPONSE_BODY_LIMIT_BYTES)
+    }
+
+    pub fn with_response_limit<'b>(server_url: &'b str, api_key: Option<&'b str>, event_body_limit_bytes: usize, batch_limit_bytes: usize, response_body_limit_bytes: usize) -> SeqCollector {
         SeqCollector {
             api_key: api_key.map(|k| k.to_owned()),
             event_body_limit_bytes: event_body_limit_bytes,
             batch_limit_bytes: batch_limit_bytes,
+            response_body_limit_bytes: response_body_limit_bytes,
             endpoint: format!("{}api/events/raw/", server_url)
         }
     }
-    
+
     pub fn new_local() -> SeqCollector {
         Self::new(LOCAL_SERVER_URL, None, DEFAULT_EVENT_BODY_LIMIT_BYTES, DEFAULT_BATCH_LIMIT_BYTES)
     }
-    
+
     fn send_batch(&self, payload: &String)  -> Result<(), Box<Error>> {
         let client = hyper::Client::new();
         let mut req = client.post(&self.endpoint)
             .body(payload)
             .header(Connection::close());
-            
+
         if let Some(ref api_key) = self.api_key {
             req = req.header(XSeqApiKey(api_key.clone()));
         }
-            
+
         let mut res = try!(req.send());
 
+        if res.status.is_success() {
+            return Ok(());
+        }
+
         let mut body = String::new();
-        try!(res.read_to_string(&mut body));
-        Ok(())
+        (&mut res).take(self.response_body_limit_bytes as u64).read_to_string(&mut body).is_ok();
+
+        let code = res.status.to_u16();
+        let retryable = code == 429 || code == 503;
+
+        Err(Box::new(SendError { status: res.status, retryable: retryable, body: body }))
     }
 }
 
 const HEADER: &'static str = "{\"Events\":[";
-const HEADER_LEN: usize = 11;
 const FOOTER: &'static str = "]}";
-const FOOTER_LEN: usize = 2;
 
 impl super::Collector for SeqCollector {
     fn dispatch(&self, events: &[events::Event]) -> Result<(), Box<Error>> {
-        let mut next = HEADER.to_owned();
-        let mut count = HEADER_LEN + FOOTER_LEN;
-        let mut delim = "";
-        
+        let mut framer = payloads::Framer::new(HEADER, FOOTER, self.batch_limit_bytes);
+
         for event in events {
             let mut payload = format_payload(event);
             if payload.len() > self.event_body_limit_bytes {
@@ -76,49 +114,26 @@ impl super::Collector for SeqCollector {
                     continue;
                 }
             }
-            
-            // Make sure at least one event is included in each batch
-            if delim != "" && count + delim.len() + payload.len() > self.batch_limit_bytes {
-                write!(next, "{}", FOOTER).is_ok();
-                try!(self.send_batch(&next));
-                
-                next = format!("{}{}", HEADER, payload);
-                count = HEADER_LEN + FOOTER_LEN + payload.len();
-                delim = ",";
-            } else {
-                write!(next, "{}{}", delim, payload).is_ok();
-                count += delim.len() + payload.len();
-                delim = ",";
-            }            
+
+            if let Some(batch) = framer.push(payload) {
+                try!(self.send_batch(&batch));
+            }
+        }
+
+        if let Some(batch) = framer.finish() {
+            try!(self.send_batch(&batch));
         }
 
-        write!(next, "{}", FOOTER).is_ok();
-        try!(self.send_batch(&next));
-        
         Ok(())
     }
 }
 
 fn format_payload(event: &events::Event) -> String {
-    let mut body = format!("{{\"Timestamp\":\"{}\",\"Level\":\"{}\",\"MessageTemplate\":{},\"Properties\":{{",
+    format!("{{\"Timestamp\":\"{}\",\"Level\":\"{}\",\"MessageTemplate\":{},\"Properties\":{}}}",
         event.timestamp().format("%FT%TZ"),
         to_seq_level(event.level()),
-        serde_json::to_string(event.message_template().text()).unwrap());
-    
-    let mut first = true;
-    for (n,v) in event.properties() {
-        
-        if !first {
-            body.push_str(",");
-        } else {
-            first = false;
-        }
-        
-        write!(&mut body, "\"{}\":{}", n, v).is_ok();            
-    }
-             
-    body.push_str("}}");
-    body     
+        serde_json::to_string(event.message_template().text()).unwrap(),
+        payloads::format_properties_json(event.properties()))
 }
 
 fn format_oversize_placeholder(event: &events::Event) -> String {
@@ -146,14 +161,14 @@ mod tests {
     use log;
     use events;
     use collectors::seq::format_payload;
-    use templates;
+    use message_templates;
     
     #[test]
     fn events_are_formatted() {
-        let timestamp = UTC.ymd(2014, 7, 8).and_hms(9, 10, 11);  
-        let mut properties: collections::BTreeMap<&'static str, String> = collections::BTreeMap::new();
-        properties.insert("number", "42".to_owned());
-        let evt = events::Event::new(timestamp, log::LogLevel::Warn, templates::MessageTemplate::new("The number is {number}"), properties);
+        let timestamp = UTC.ymd(2014, 7, 8).and_hms(9, 10, 11);
+        let mut properties: collections::BTreeMap<&'static str, events::Value> = collections::BTreeMap::new();
+        properties.insert("number", events::Value::I64(42));
+        let evt = events::Event::new(timestamp, log::LogLevel::Warn, message_templates::MessageTemplate::new("The number is {number}"), properties);
         let payload = format_payload(&evt);
         assert_eq!(payload, "{\"Timestamp\":\"2014-07-08T09:10:11Z\",\"Level\":\"Warning\",\"MessageTemplate\":\"The number is {number}\",\"Properties\":{\"number\":42}}".to_owned());
     }
@@ -0,0 +1,29 @@
+use std::error::Error;
+use std::io::{self, Write};
+
+use events;
+use message_templates;
+
+/// Renders events as human-readable lines on stdout, e.g.
+/// `2016-01-02T03:04:05Z [INFO] User nblumhardt exceeded quota of 42!`
+pub struct StdoutCollector;
+
+impl StdoutCollector {
+    pub fn new() -> StdoutCollector {
+        StdoutCollector
+    }
+}
+
+impl super::Collector for StdoutCollector {
+    fn dispatch(&self, events: &[events::Event]) -> Result<(), Box<Error>> {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+
+        for event in events {
+            let line = message_templates::render(event.message_template(), event.properties());
+            try!(writeln!(out, "{} [{}] {}", event.timestamp().format("%FT%TZ"), event.level(), line));
+        }
+
+        Ok(())
+    }
+}
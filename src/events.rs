@@ -4,23 +4,170 @@ use std::collections::btree_map::Entry;
 use log::LogLevel;
 use serde;
 use serde_json;
-use templates;
+use message_templates;
 
-/// Converts an arbitrary serializable object into the internal property format
-/// carried on events (currently JSON values...)
-pub fn capture_property_value<T: serde::ser::Serialize>(v: &T) -> String {
-    serde_json::to_string(v).unwrap()
+/// A captured property value.
+///
+/// Keeping scalars typed (rather than pre-serializing everything to a JSON
+/// string) lets collectors emit correctly-typed fields instead of having to
+/// re-parse or blindly splice JSON fragments together.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    Str(String),
+    /// Anything that isn't a recognized scalar, kept as a JSON value.
+    Json(serde_json::Value),
 }
 
+impl Value {
+    pub fn to_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        match *self {
+            Value::I64(n) => Some(n),
+            Value::U64(n) if n <= i64::max_value() as u64 => Some(n as i64),
+            _ => None,
+        }
+    }
+
+    pub fn to_f64(&self) -> Option<f64> {
+        match *self {
+            Value::F64(n) => Some(n),
+            Value::I64(n) => Some(n as f64),
+            Value::U64(n) => Some(n as f64),
+            _ => None,
+        }
+    }
+
+    pub fn to_str(&self) -> Option<&str> {
+        match *self {
+            Value::Str(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Renders the value as a JSON fragment, for collectors that speak JSON.
+    pub fn to_json(&self) -> String {
+        match *self {
+            Value::Null => "null".to_owned(),
+            Value::Bool(b) => b.to_string(),
+            Value::I64(n) => n.to_string(),
+            Value::U64(n) => n.to_string(),
+            Value::F64(n) => n.to_string(),
+            Value::Str(ref s) => serde_json::to_string(s).unwrap(),
+            Value::Json(ref j) => serde_json::to_string(j).unwrap(),
+        }
+    }
+}
+
+/// Converts a value into the `Value` representation events carry.
+pub trait ToValue {
+    fn to_value(&self) -> Value;
+}
+
+impl ToValue for Value {
+    fn to_value(&self) -> Value {
+        self.clone()
+    }
+}
+
+impl ToValue for bool {
+    fn to_value(&self) -> Value {
+        Value::Bool(*self)
+    }
+}
+
+impl ToValue for str {
+    fn to_value(&self) -> Value {
+        Value::Str(self.to_owned())
+    }
+}
+
+impl ToValue for String {
+    fn to_value(&self) -> Value {
+        Value::Str(self.clone())
+    }
+}
+
+macro_rules! impl_to_value_for_ints {
+    ($( $t:ty ),*) => {
+        $(
+            impl ToValue for $t {
+                fn to_value(&self) -> Value {
+                    Value::I64(*self as i64)
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_to_value_for_uints {
+    ($( $t:ty ),*) => {
+        $(
+            impl ToValue for $t {
+                fn to_value(&self) -> Value {
+                    Value::U64(*self as u64)
+                }
+            }
+        )*
+    };
+}
+
+impl_to_value_for_ints!(i8, i16, i32, i64, isize);
+impl_to_value_for_uints!(u8, u16, u32, u64, usize);
+
+impl ToValue for f32 {
+    fn to_value(&self) -> Value {
+        Value::F64(*self as f64)
+    }
+}
+
+impl ToValue for f64 {
+    fn to_value(&self) -> Value {
+        Value::F64(*self)
+    }
+}
+
+impl ToValue for serde_json::Value {
+    fn to_value(&self) -> Value {
+        match *self {
+            serde_json::Value::Null => Value::Null,
+            serde_json::Value::Bool(b) => Value::Bool(b),
+            serde_json::Value::I64(n) => Value::I64(n),
+            serde_json::Value::U64(n) => Value::U64(n),
+            serde_json::Value::F64(n) => Value::F64(n),
+            serde_json::Value::String(ref s) => Value::Str(s.clone()),
+            ref other => Value::Json(other.clone()),
+        }
+    }
+}
+
+/// Captures an arbitrary serializable object into the `Value` representation
+/// carried on events, recognizing scalars and falling back to `Value::Json`
+/// for anything else.
+pub fn capture_property_value<T: serde::ser::Serialize>(v: &T) -> Value {
+    serde_json::to_value(v).to_value()
+}
+
+#[derive(Clone)]
 pub struct Event {
     timestamp: DateTime<UTC>,
     level: LogLevel,
-    message_template: templates::MessageTemplate,
-    properties: collections::BTreeMap<&'static str, String>
+    message_template: message_templates::MessageTemplate,
+    properties: collections::BTreeMap<&'static str, Value>
 }
 
 impl Event {
-    pub fn new(timestamp: DateTime<UTC>, level: LogLevel, message_template: templates::MessageTemplate, properties: collections::BTreeMap<&'static str, String>) -> Event {
+    pub fn new(timestamp: DateTime<UTC>, level: LogLevel, message_template: message_templates::MessageTemplate, properties: collections::BTreeMap<&'static str, Value>) -> Event {
         Event {
             timestamp: timestamp,
             level: level,
@@ -28,37 +175,89 @@ impl Event {
             properties: properties
         }
     }
-    
-    pub fn new_now(level: LogLevel, message_template: templates::MessageTemplate, properties: collections::BTreeMap<&'static str, String>) -> Event {
+
+    pub fn new_now(level: LogLevel, message_template: message_templates::MessageTemplate, properties: collections::BTreeMap<&'static str, Value>) -> Event {
         Self::new(UTC::now(), level, message_template, properties)
     }
-    
+
     pub fn timestamp(&self) -> DateTime<UTC> {
         self.timestamp
     }
-    
+
     pub fn level(&self) -> LogLevel {
         self.level
     }
-    
-    pub fn message_template(&self) -> &templates::MessageTemplate {
+
+    pub fn message_template(&self) -> &message_templates::MessageTemplate {
         &self.message_template
     }
-    
-    pub fn properties(&self) -> &collections::BTreeMap<&'static str, String> {
+
+    pub fn properties(&self) -> &collections::BTreeMap<&'static str, Value> {
         &self.properties
     }
-    
-    pub fn add_or_update_property(&mut self, name: &'static str, value: String) {
+
+    pub fn add_or_update_property<T: ToValue>(&mut self, name: &'static str, value: T) {
         match self.properties.entry(name) {
-            Entry::Vacant(v) => {v.insert(value);},
-            Entry::Occupied(mut o) => {o.insert(value);}
+            Entry::Vacant(v) => {v.insert(value.to_value());},
+            Entry::Occupied(mut o) => {o.insert(value.to_value());}
         }
     }
-    
-    pub fn add_property_if_absent(&mut self, name: &'static str, value: String) {
+
+    pub fn add_property_if_absent<T: ToValue>(&mut self, name: &'static str, value: T) {
         if !self.properties.contains_key(name) {
-            self.properties.insert(name, value);
+            self.properties.insert(name, value.to_value());
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ToValue, Value};
+
+    #[test]
+    fn to_bool_only_recognizes_bool() {
+        assert_eq!(Some(true), Value::Bool(true).to_bool());
+        assert_eq!(None, Value::I64(1).to_bool());
+    }
+
+    #[test]
+    fn to_i64_widens_u64_within_range_but_not_out_of_range() {
+        assert_eq!(Some(42), Value::I64(42).to_i64());
+        assert_eq!(Some(42), Value::U64(42).to_i64());
+        assert_eq!(None, Value::U64(u64::max_value()).to_i64());
+        assert_eq!(None, Value::Str("42".to_owned()).to_i64());
+    }
+
+    #[test]
+    fn to_f64_widens_every_numeric_variant() {
+        assert_eq!(Some(1.5), Value::F64(1.5).to_f64());
+        assert_eq!(Some(42.0), Value::I64(42).to_f64());
+        assert_eq!(Some(42.0), Value::U64(42).to_f64());
+        assert_eq!(None, Value::Bool(true).to_f64());
+    }
+
+    #[test]
+    fn to_str_only_recognizes_str() {
+        assert_eq!(Some("nblumhardt"), Value::Str("nblumhardt".to_owned()).to_str());
+        assert_eq!(None, Value::I64(1).to_str());
+    }
+
+    #[test]
+    fn to_json_renders_every_variant_as_a_json_fragment() {
+        assert_eq!("null", Value::Null.to_json());
+        assert_eq!("true", Value::Bool(true).to_json());
+        assert_eq!("42", Value::I64(42).to_json());
+        assert_eq!("42", Value::U64(42).to_json());
+        assert_eq!("1.5", Value::F64(1.5).to_json());
+        assert_eq!("\"nblumhardt\"", Value::Str("nblumhardt".to_owned()).to_json());
+    }
+
+    #[test]
+    fn to_value_captures_native_types_as_their_matching_variant() {
+        assert_eq!(Value::Bool(true), true.to_value());
+        assert_eq!(Value::Str("hi".to_owned()), "hi".to_value());
+        assert_eq!(Value::I64(42), 42i32.to_value());
+        assert_eq!(Value::U64(42), 42u32.to_value());
+        assert_eq!(Value::F64(1.5), 1.5f64.to_value());
+    }
+}
@@ -1,11 +1,17 @@
 extern crate serde;
 extern crate serde_json;
 extern crate chrono;
+#[macro_use]
 extern crate hyper;
 
 #[macro_use]
-extern crate log;
+pub extern crate log;
+
+#[macro_use]
+extern crate lazy_static;
 
+pub mod collectors;
+pub mod events;
 pub mod message_templates;
 pub mod payloads;
 pub mod pipeline;
@@ -20,7 +26,7 @@ macro_rules! __emit_get_event_data {
 
         // Underscores avoid the unused_mut warning
         let mut _names: Vec<&str> = vec![];
-        let mut _properties: collections::BTreeMap<&'static str, String> = collections::BTreeMap::new();
+        let mut _properties: collections::BTreeMap<&'static str, $crate::events::Value> = collections::BTreeMap::new();
 
         $(
             _names.push(stringify!($n));
@@ -33,43 +39,103 @@ macro_rules! __emit_get_event_data {
     }};
 }
 
+/// Like `emit!`, but captures the event at an explicit `LogLevel` instead of
+/// always using `Info`. This is what per-target level filtering (see
+/// `PipelineBuilder::send_to`) actually filters on.
+#[macro_export]
+macro_rules! emit_at {
+    ( $lvl:expr, $s:expr, $( $n:ident: $v:expr ),* ) => {{
+        if $crate::pipeline::should_emit($lvl) {
+            let (template, properties) = __emit_get_event_data!($s, $($n: $v),*);
+            $crate::pipeline::emit($lvl, &template, &properties);
+        }
+    }};
+
+    ( $lvl:expr, $s:expr ) => {{
+        emit_at!($lvl, $s,);
+    }};
+}
+
 #[macro_export]
 macro_rules! emit {
     ( $s:expr, $( $n:ident: $v:expr ),* ) => {{
-        let (template, properties) = __emit_get_event_data!($s, $($n: $v),*);
-        $crate::pipeline::emit(&template, &properties);
+        emit_at!($crate::log::LogLevel::Info, $s, $($n: $v),*);
     }};
-    
+
     ( $s:expr ) => {{
         emit!($s,);
     }};
 }
 
+/// Emits at `LogLevel::Trace`. See `emit!` for template/property syntax.
+#[macro_export]
+macro_rules! emit_trace {
+    ( $s:expr, $( $n:ident: $v:expr ),* ) => {{ emit_at!($crate::log::LogLevel::Trace, $s, $($n: $v),*); }};
+    ( $s:expr ) => {{ emit_trace!($s,); }};
+}
+
+/// Emits at `LogLevel::Debug`. See `emit!` for template/property syntax.
+#[macro_export]
+macro_rules! emit_debug {
+    ( $s:expr, $( $n:ident: $v:expr ),* ) => {{ emit_at!($crate::log::LogLevel::Debug, $s, $($n: $v),*); }};
+    ( $s:expr ) => {{ emit_debug!($s,); }};
+}
+
+/// Emits at `LogLevel::Warn`. See `emit!` for template/property syntax.
+#[macro_export]
+macro_rules! emit_warn {
+    ( $s:expr, $( $n:ident: $v:expr ),* ) => {{ emit_at!($crate::log::LogLevel::Warn, $s, $($n: $v),*); }};
+    ( $s:expr ) => {{ emit_warn!($s,); }};
+}
+
+/// Emits at `LogLevel::Error`. See `emit!` for template/property syntax.
+#[macro_export]
+macro_rules! emit_error {
+    ( $s:expr, $( $n:ident: $v:expr ),* ) => {{ emit_at!($crate::log::LogLevel::Error, $s, $($n: $v),*); }};
+    ( $s:expr ) => {{ emit_error!($s,); }};
+}
+
 #[cfg(test)]
 mod tests {
     use pipeline;
-    
+    use events::Value;
+
     #[test]
     fn unparameterized_templates_are_captured() {
         let (template, properties) = __emit_get_event_data!("Starting...",);
         assert!(template == "Starting...");
         assert!(properties.len() == 0);
     }
-    
+
     #[test]
     fn template_and_properties_are_captured() {
         let u = "nblumhardt";
-        let q = 42;
-        
+        let q: i64 = 42;
+
         let (template, properties) = __emit_get_event_data!("User {} exceeded quota of {}!", user: u, quota: q);
         assert!(template == "User {user} exceeded quota of {quota}!");
-        assert!(properties.get("user") == Some(&"\"nblumhardt\"".to_owned()));
-        assert!(properties.get("quota") == Some(&"42".to_owned()));
-    }    
+        assert!(properties.get("user") == Some(&Value::Str("nblumhardt".to_owned())));
+        assert!(properties.get("quota") == Some(&Value::I64(42)));
+    }
 
     #[test]
     pub fn emitted_events_are_flushed() {
         let _flush = pipeline::init("http://localhost:5341/", None);
         emit!("Hello");
     }
+
+    #[test]
+    pub fn level_specific_macros_are_exercised_by_filtering() {
+        use collectors::stdout::StdoutCollector;
+        use log::LogLevel;
+
+        let _flush = pipeline::PipelineBuilder::new()
+            .send_to(StdoutCollector::new(), LogLevel::Warn)
+            .init();
+
+        assert!(pipeline::should_emit(LogLevel::Warn));
+        assert!(!pipeline::should_emit(LogLevel::Info));
+
+        emit_warn!("Disk usage at {percent}%", percent: 91);
+    }
 }
@@ -0,0 +1,119 @@
+use std::collections;
+
+use serde;
+use serde_json;
+
+use events;
+use events::Value;
+
+/// A message template as captured at an `emit!` call site: the original
+/// format string with positional `{}` holes rewritten to the names of the
+/// properties that filled them.
+#[derive(Clone)]
+pub struct MessageTemplate {
+    text: String,
+}
+
+impl MessageTemplate {
+    pub fn new<T: Into<String>>(text: T) -> MessageTemplate {
+        MessageTemplate { text: text.into() }
+    }
+
+    pub fn text(&self) -> &String {
+        &self.text
+    }
+}
+
+/// Rewrites the positional `{}` holes in `s` with `names`, in the order
+/// they were passed to `emit!`, e.g. `("{} logged in", ["user"])` becomes
+/// `"{user} logged in"`.
+pub fn build_template(s: &str, names: &[&str]) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut names = names.iter();
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '{' && chars.peek() == Some(&'}') {
+            chars.next();
+
+            match names.next() {
+                Some(name) => {
+                    result.push('{');
+                    result.push_str(name);
+                    result.push('}');
+                }
+                None => result.push_str("{}"),
+            }
+        } else {
+            result.push(c);
+        }
+    }
+
+    result
+}
+
+/// Captures a property value for inclusion on an event.
+pub fn capture<T: serde::ser::Serialize>(v: &T) -> Value {
+    events::capture_property_value(v)
+}
+
+/// Renders a template with its captured properties substituted in, e.g.
+/// `"User {user} exceeded quota of {quota}!"` with `user = "nblumhardt"` and
+/// `quota = 42` becomes `"User nblumhardt exceeded quota of 42!"`. Holes with
+/// no matching property are left as-is.
+pub fn render(template: &MessageTemplate, properties: &collections::BTreeMap<&'static str, Value>) -> String {
+    let text = template.text();
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '{' {
+            result.push(c);
+            continue;
+        }
+
+        let mut name = String::new();
+        let mut closed = false;
+
+        while let Some(&next) = chars.peek() {
+            if next == '}' {
+                chars.next();
+                closed = true;
+                break;
+            }
+            name.push(next);
+            chars.next();
+        }
+
+        if !closed {
+            result.push('{');
+            result.push_str(&name);
+            continue;
+        }
+
+        match properties.get(name.as_str()) {
+            Some(value) => result.push_str(&display_value(value)),
+            None => {
+                result.push('{');
+                result.push_str(&name);
+                result.push('}');
+            }
+        }
+    }
+
+    result
+}
+
+// Strings render without their surrounding quotes so they read naturally in
+// a human-facing line; everything else renders as its JSON form.
+fn display_value(value: &Value) -> String {
+    match *value {
+        Value::Str(ref s) => s.clone(),
+        Value::Null => "null".to_owned(),
+        Value::Bool(b) => b.to_string(),
+        Value::I64(n) => n.to_string(),
+        Value::U64(n) => n.to_string(),
+        Value::F64(n) => n.to_string(),
+        Value::Json(ref j) => serde_json::to_string(j).unwrap(),
+    }
+}
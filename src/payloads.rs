@@ -0,0 +1,142 @@
+use std::collections;
+use std::fmt::Write;
+
+use events::Value;
+
+/// Serializes an event's captured properties into a JSON object body, e.g.
+/// `{"quota":42,"user":"nblumhardt"}` (keys in sorted order). Shared by collectors that emit JSON,
+/// such as `SeqCollector` and `FileCollector`.
+pub fn format_properties_json(properties: &collections::BTreeMap<&'static str, Value>) -> String {
+    let mut body = String::from("{");
+    let mut first = true;
+
+    for (n, v) in properties {
+        if !first {
+            body.push_str(",");
+        } else {
+            first = false;
+        }
+
+        write!(body, "\"{}\":{}", n, v.to_json()).is_ok();
+    }
+
+    body.push_str("}");
+    body
+}
+
+/// Accumulates formatted per-event payloads into batches bounded by a byte
+/// limit, wrapping each completed batch in a wire format's framing (e.g.
+/// Seq's `{"Events":[...]}` or an OTLP `logRecords` array). At least one
+/// payload is always included in a batch, even if it alone exceeds the
+/// limit, so a single oversize event can't stall a collector forever.
+pub struct Framer {
+    header: &'static str,
+    footer: &'static str,
+    limit_bytes: usize,
+    next: String,
+    count: usize,
+    delim: &'static str,
+}
+
+impl Framer {
+    pub fn new(header: &'static str, footer: &'static str, limit_bytes: usize) -> Framer {
+        Framer {
+            header: header,
+            footer: footer,
+            limit_bytes: limit_bytes,
+            next: header.to_owned(),
+            count: header.len() + footer.len(),
+            delim: "",
+        }
+    }
+
+    /// Adds a payload to the in-progress batch. Returns a completed batch
+    /// (with framing closed) if adding `payload` would have exceeded the
+    /// byte limit; in that case `payload` starts the next batch instead.
+    pub fn push(&mut self, payload: String) -> Option<String> {
+        if self.delim != "" && self.count + self.delim.len() + payload.len() > self.limit_bytes {
+            let mut completed = self.next.clone();
+            completed.push_str(self.footer);
+
+            self.next = format!("{}{}", self.header, payload);
+            self.count = self.header.len() + self.footer.len() + payload.len();
+            self.delim = ",";
+
+            Some(completed)
+        } else {
+            self.next.push_str(self.delim);
+            self.next.push_str(&payload);
+            self.count += self.delim.len() + payload.len();
+            self.delim = ",";
+
+            None
+        }
+    }
+
+    /// Closes and returns the final in-progress batch, or `None` if no
+    /// payload was ever pushed.
+    pub fn finish(mut self) -> Option<String> {
+        if self.delim == "" {
+            return None;
+        }
+
+        self.next.push_str(self.footer);
+        Some(self.next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use events::Value;
+    use super::{format_properties_json, Framer};
+
+    #[test]
+    fn format_properties_json_orders_by_key_and_preserves_types() {
+        let mut properties: BTreeMap<&'static str, Value> = BTreeMap::new();
+        properties.insert("user", Value::Str("nblumhardt".to_owned()));
+        properties.insert("quota", Value::I64(42));
+
+        assert_eq!("{\"quota\":42,\"user\":\"nblumhardt\"}", format_properties_json(&properties));
+    }
+
+    #[test]
+    fn format_properties_json_of_an_empty_map_is_an_empty_object() {
+        let properties: BTreeMap<&'static str, Value> = BTreeMap::new();
+        assert_eq!("{}", format_properties_json(&properties));
+    }
+
+    #[test]
+    fn finish_returns_none_when_nothing_was_pushed() {
+        let framer = Framer::new("[", "]", 1024);
+        assert_eq!(None, framer.finish());
+    }
+
+    #[test]
+    fn payloads_under_the_limit_accumulate_into_one_batch() {
+        let mut framer = Framer::new("[", "]", 1024);
+
+        assert_eq!(None, framer.push("\"a\"".to_owned()));
+        assert_eq!(None, framer.push("\"b\"".to_owned()));
+
+        assert_eq!(Some("[\"a\",\"b\"]".to_owned()), framer.finish());
+    }
+
+    #[test]
+    fn a_payload_that_would_exceed_the_limit_starts_the_next_batch() {
+        let mut framer = Framer::new("[", "]", "[\"a\"]".len());
+
+        assert_eq!(None, framer.push("\"a\"".to_owned()));
+        assert_eq!(Some("[\"a\"]".to_owned()), framer.push("\"b\"".to_owned()));
+        assert_eq!(Some("[\"b\"]".to_owned()), framer.finish());
+    }
+
+    #[test]
+    fn a_single_oversized_payload_is_still_included_alone() {
+        let mut framer = Framer::new("[", "]", 1);
+
+        assert_eq!(None, framer.push("\"much too long for the limit\"".to_owned()));
+        assert_eq!(Some("[\"much too long for the limit\"]".to_owned()), framer.finish());
+    }
+}
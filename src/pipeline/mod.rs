@@ -0,0 +1,422 @@
+//! Drives captured events from `emit!` call sites to their collectors.
+//!
+//! `emit` never talks to a collector directly: it pushes the event onto a
+//! bounded ring buffer and returns immediately, while a single background
+//! worker thread drains the buffer in batches and fans each batch out to
+//! every registered `Subscription` whose level filter and predicate accept
+//! it. This keeps logging off the hot path at the cost of at-most-"overflow
+//! policy" delivery guarantees under sustained backpressure.
+
+use std::cmp;
+use std::collections::{self, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::LogLevel;
+
+use collectors::{self, Collector};
+use collectors::seq::{self, SeqCollector};
+use events::{self, Event};
+use message_templates::{self, MessageTemplate};
+
+mod ring_buffer;
+mod spool;
+
+use self::ring_buffer::RingBuffer;
+use self::spool::Spool;
+
+const DEFAULT_CAPACITY: usize = 10_000;
+const DEFAULT_BATCH_SIZE: usize = 100;
+const DEFAULT_FLUSH_INTERVAL_MS: u64 = 1_000;
+const INITIAL_RETRY_BACKOFF_MS: u64 = 500;
+const MAX_RETRY_BACKOFF_MS: u64 = 60_000;
+
+/// What happens to newly emitted events once the ring buffer is full.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OverflowPolicy {
+    /// Discard the oldest buffered event to make room for the new one.
+    DropOldest,
+    /// Discard the event that would have overflowed the buffer.
+    DropNewest,
+    /// Block the emitting thread until the worker drains some capacity.
+    Block,
+}
+
+lazy_static! {
+    static ref CURRENT: RwLock<Option<Arc<RingBuffer>>> = RwLock::new(None);
+    static ref MAX_LEVEL: AtomicUsize = AtomicUsize::new(0);
+}
+
+/// Fast-path check used by the `emit!` macro before it captures any
+/// properties. Returns `false` (skipping capture entirely) when `level` is
+/// more verbose than every registered subscription's filter.
+pub fn should_emit(level: LogLevel) -> bool {
+    level as usize <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Pushes a captured event onto the active pipeline's ring buffer, if one
+/// has been `init`-ed. Returns immediately; the event is fanned out to its
+/// collector(s) by the background worker.
+pub fn emit(level: LogLevel, template: &str, properties: &collections::BTreeMap<&'static str, events::Value>) {
+    let current = CURRENT.read().unwrap();
+
+    if let Some(ref ring) = *current {
+        let event = Event::new_now(level, MessageTemplate::new(template.to_owned()), properties.clone());
+        ring.push(event);
+    }
+}
+
+/// A single registered destination: a collector, the least-severe level it
+/// wants to see, and an optional predicate for finer-grained selection.
+pub struct Subscription {
+    collector: Box<Collector>,
+    level_filter: LogLevel,
+    predicate: Option<Box<Fn(&Event) -> bool + Send + Sync>>,
+}
+
+impl Subscription {
+    fn accepts(&self, event: &Event) -> bool {
+        event.level() <= self.level_filter && self.predicate.as_ref().map_or(true, |p| p(event))
+    }
+}
+
+/// Builds a pipeline by registering one or more collectors, each with its
+/// own level filter and optional predicate, then spawns the background
+/// worker via `init`.
+pub struct PipelineBuilder {
+    subscriptions: Vec<Subscription>,
+    capacity: usize,
+    batch_size: usize,
+    flush_interval: Duration,
+    overflow_policy: OverflowPolicy,
+    spool: Option<(PathBuf, u64)>,
+}
+
+impl PipelineBuilder {
+    pub fn new() -> PipelineBuilder {
+        PipelineBuilder {
+            subscriptions: Vec::new(),
+            capacity: DEFAULT_CAPACITY,
+            batch_size: DEFAULT_BATCH_SIZE,
+            flush_interval: Duration::from_millis(DEFAULT_FLUSH_INTERVAL_MS),
+            overflow_policy: OverflowPolicy::DropOldest,
+            spool: None,
+        }
+    }
+
+    /// Registers a collector that receives every event at `level_filter` or
+    /// more severe.
+    pub fn send_to<C: Collector + 'static>(mut self, collector: C, level_filter: LogLevel) -> PipelineBuilder {
+        self.subscriptions.push(Subscription {
+            collector: Box::new(collector),
+            level_filter: level_filter,
+            predicate: None,
+        });
+        self
+    }
+
+    /// Registers a collector that receives events at `level_filter` or more
+    /// severe, and for which `predicate` also returns `true`.
+    pub fn send_to_if<C, F>(mut self, collector: C, level_filter: LogLevel, predicate: F) -> PipelineBuilder
+        where C: Collector + 'static, F: Fn(&Event) -> bool + Send + Sync + 'static
+    {
+        self.subscriptions.push(Subscription {
+            collector: Box::new(collector),
+            level_filter: level_filter,
+            predicate: Some(Box::new(predicate)),
+        });
+        self
+    }
+
+    pub fn capacity(mut self, capacity: usize) -> PipelineBuilder {
+        self.capacity = capacity;
+        self
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> PipelineBuilder {
+        self.batch_size = batch_size;
+        self
+    }
+
+    pub fn flush_interval(mut self, flush_interval: Duration) -> PipelineBuilder {
+        self.flush_interval = flush_interval;
+        self
+    }
+
+    pub fn overflow_policy(mut self, overflow_policy: OverflowPolicy) -> PipelineBuilder {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// Durably buffers batches to `dir` before dispatch, so they survive a
+    /// process restart while every collector is unreachable. Uses
+    /// `spool::DEFAULT_CAPACITY_BYTES` as the total on-disk size cap; see
+    /// `spool_to_with_capacity` to choose a different one.
+    pub fn spool_to<P: Into<PathBuf>>(self, dir: P) -> PipelineBuilder {
+        self.spool_to_with_capacity(dir, spool::DEFAULT_CAPACITY_BYTES)
+    }
+
+    /// Like `spool_to`, but with an explicit total size cap for the spool
+    /// directory. Once exceeded, the oldest surviving segments are dropped
+    /// to make room.
+    pub fn spool_to_with_capacity<P: Into<PathBuf>>(mut self, dir: P, capacity_bytes: u64) -> PipelineBuilder {
+        self.spool = Some((dir.into(), capacity_bytes));
+        self
+    }
+
+    /// Spawns the background worker and installs this pipeline as the
+    /// target of `emit!` calls process-wide.
+    pub fn init(self) -> FlushGuard {
+        let ring = Arc::new(RingBuffer::new(self.capacity, self.overflow_policy));
+
+        let max_level = self.subscriptions.iter().map(|s| s.level_filter as usize).max().unwrap_or(0);
+        MAX_LEVEL.store(max_level, Ordering::Relaxed);
+
+        {
+            let mut current = CURRENT.write().unwrap();
+            *current = Some(ring.clone());
+        }
+
+        let subscriptions = self.subscriptions;
+
+        let (spool, replay) = match self.spool {
+            Some((dir, capacity_bytes)) => match Spool::open(dir, capacity_bytes) {
+                Ok((spool, segments)) => (Some(spool), segments),
+                Err(e) => {
+                    error!("Failed to open the spool directory: {}", e);
+                    (None, Vec::new())
+                }
+            },
+            None => (None, Vec::new()),
+        };
+
+        let worker_ring = ring.clone();
+        let batch_size = self.batch_size;
+        let flush_interval = self.flush_interval;
+
+        let worker = thread::spawn(move || {
+            // Crash-surviving segments are queued as pending retries rather
+            // than replayed to completion up front: a segment stuck against
+            // a still-down collector would otherwise starve every live event
+            // emitted during the outage (see `Worker::run`).
+            let pending = replay.into_iter()
+                .map(|segment| PendingSegment::new(subscriptions.len(), segment))
+                .collect();
+
+            let worker = Worker {
+                ring: worker_ring,
+                subscriptions: subscriptions,
+                spool: spool,
+                pending: pending,
+            };
+
+            worker.run(batch_size, flush_interval);
+        });
+
+        FlushGuard {
+            ring: ring,
+            worker: Some(worker),
+        }
+    }
+}
+
+/// A handle returned by `init`. Dropping it signals the background worker
+/// to shut down, flushes any buffered events (including a partial batch),
+/// and blocks until the worker thread has joined.
+pub struct FlushGuard {
+    ring: Arc<RingBuffer>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Drop for FlushGuard {
+    fn drop(&mut self) {
+        self.ring.shutdown();
+
+        if let Some(worker) = self.worker.take() {
+            worker.join().is_ok();
+        }
+
+        *CURRENT.write().unwrap() = None;
+        MAX_LEVEL.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Initializes the pipeline with a single `SeqCollector` receiving every
+/// event, and default batching/overflow settings. For multiple collectors
+/// or per-collector filtering, use `PipelineBuilder` directly.
+pub fn init(server_url: &str, api_key: Option<&str>) -> FlushGuard {
+    let collector = SeqCollector::new(server_url, api_key, seq::DEFAULT_EVENT_BODY_LIMIT_BYTES, seq::DEFAULT_BATCH_LIMIT_BYTES);
+    PipelineBuilder::new().send_to(collector, LogLevel::Trace).init()
+}
+
+/// A spooled segment still awaiting acceptance from one or more
+/// subscriptions, with its own independent backoff. `done[i]` tracks
+/// whether `subscriptions[i]` has already accepted this segment, so a
+/// collector that's healthy isn't re-sent the same batch on every retry of
+/// a sibling collector that's down.
+struct PendingSegment {
+    segment: spool::Segment,
+    done: Vec<bool>,
+    backoff_ms: u64,
+    next_attempt: Instant,
+}
+
+impl PendingSegment {
+    fn new(subscription_count: usize, segment: spool::Segment) -> PendingSegment {
+        PendingSegment {
+            segment: segment,
+            done: vec![false; subscription_count],
+            backoff_ms: INITIAL_RETRY_BACKOFF_MS,
+            next_attempt: Instant::now(),
+        }
+    }
+}
+
+/// Owns the background worker's state: the ring buffer it drains, the
+/// subscriptions it fans batches out to, the optional spool backing durable
+/// delivery, and any segments still being retried.
+struct Worker {
+    ring: Arc<RingBuffer>,
+    subscriptions: Vec<Subscription>,
+    spool: Option<Spool>,
+    pending: VecDeque<PendingSegment>,
+}
+
+impl Worker {
+    /// Drains and dispatches batches until shut down and empty, interleaving
+    /// attempts to clear `pending` on every iteration so a segment retrying
+    /// against a down collector never blocks live events from being
+    /// dispatched to the rest.
+    fn run(mut self, batch_size: usize, flush_interval: Duration) {
+        loop {
+            self.advance_pending();
+
+            let batch = self.ring.drain(batch_size, flush_interval);
+            if !batch.is_empty() {
+                self.dispatch_batch(batch);
+            }
+
+            let dropped = self.ring.take_dropped();
+            if dropped > 0 {
+                self.dispatch_batch(vec![dropped_diagnostic(dropped)]);
+            }
+
+            if self.ring.is_shutdown() && self.ring.is_empty() && self.pending.is_empty() {
+                break;
+            }
+        }
+    }
+
+    /// Hands a batch to every matching subscription, spooling it to disk
+    /// first and queuing it for retry if `spool` is set and not every
+    /// subscription accepted it on the first attempt.
+    fn dispatch_batch(&mut self, batch: Vec<Event>) {
+        match self.spool {
+            None => { dispatch_to_subscriptions(&self.subscriptions, &batch); }
+            Some(ref spool) => match spool.write(batch) {
+                Ok(segment) => {
+                    let mut pending = PendingSegment::new(self.subscriptions.len(), segment);
+
+                    if dispatch_pending(&self.subscriptions, &pending.segment.events, &mut pending.done) {
+                        spool.remove(&pending.segment);
+                    } else {
+                        self.pending.push_back(pending);
+                    }
+                }
+                Err(e) => error!("Failed to spool a batch of events: {}", e),
+            },
+        }
+    }
+
+    /// Retries every segment in `pending` whose backoff has elapsed,
+    /// dropping it once every subscription has accepted it. A segment that's
+    /// still failing when the pipeline shuts down is dropped from `pending`
+    /// but left on disk, for the next startup's replay to pick back up.
+    fn advance_pending(&mut self) {
+        if self.spool.is_none() || self.pending.is_empty() {
+            return;
+        }
+
+        let spool = self.spool.as_ref().unwrap();
+        let now = Instant::now();
+        let shutting_down = self.ring.is_shutdown();
+
+        let mut remaining = VecDeque::with_capacity(self.pending.len());
+
+        while let Some(mut entry) = self.pending.pop_front() {
+            if now < entry.next_attempt {
+                remaining.push_back(entry);
+                continue;
+            }
+
+            if dispatch_pending(&self.subscriptions, &entry.segment.events, &mut entry.done) {
+                spool.remove(&entry.segment);
+                continue;
+            }
+
+            if shutting_down {
+                continue;
+            }
+
+            entry.next_attempt = now + Duration::from_millis(entry.backoff_ms);
+            entry.backoff_ms = cmp::min(entry.backoff_ms * 2, MAX_RETRY_BACKOFF_MS);
+            remaining.push_back(entry);
+        }
+
+        self.pending = remaining;
+    }
+}
+
+/// Returns `true` if every subscription that matched an event in `batch`
+/// dispatched it successfully, `false` if at least one failed in a way
+/// worth retrying. Permanent failures are logged but don't trigger a retry.
+fn dispatch_to_subscriptions(subscriptions: &[Subscription], batch: &[Event]) -> bool {
+    let mut done = vec![false; subscriptions.len()];
+    dispatch_pending(subscriptions, batch, &mut done)
+}
+
+/// Like `dispatch_to_subscriptions`, but dispatches only to subscriptions
+/// not already marked `done`, and marks each one `done` once it accepts
+/// `batch` or fails in a way that isn't worth retrying. Returns `true` once
+/// every subscription is done.
+fn dispatch_pending(subscriptions: &[Subscription], batch: &[Event], done: &mut [bool]) -> bool {
+    for (subscription, done) in subscriptions.iter().zip(done.iter_mut()) {
+        if *done {
+            continue;
+        }
+
+        let matching: Vec<Event> = batch.iter().filter(|e| subscription.accepts(e)).cloned().collect();
+
+        if matching.is_empty() {
+            *done = true;
+            continue;
+        }
+
+        match subscription.collector.dispatch(&matching) {
+            Ok(()) => *done = true,
+            Err(e) => {
+                error!("Failed to dispatch a batch of {} events: {}", matching.len(), e);
+
+                if !collectors::is_retryable(&*e) {
+                    *done = true;
+                }
+            }
+        }
+    }
+
+    done.iter().all(|&d| d)
+}
+
+fn dropped_diagnostic(dropped: usize) -> Event {
+    let mut properties = collections::BTreeMap::new();
+    properties.insert("dropped_events", message_templates::capture(&dropped));
+
+    Event::new_now(
+        LogLevel::Warn,
+        MessageTemplate::new("{dropped_events} events were dropped from the pipeline's ring buffer".to_owned()),
+        properties,
+    )
+}
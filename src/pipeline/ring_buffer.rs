@@ -0,0 +1,225 @@
+use std::collections::VecDeque;
+use std::cmp;
+use std::sync::{Condvar, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use events::Event;
+use super::OverflowPolicy;
+
+/// A bounded queue of captured events sitting between emitting threads and
+/// the background worker that drains them.
+///
+/// Producers never block the emitting thread unless `OverflowPolicy::Block`
+/// is in effect; `DropOldest`/`DropNewest` make room (or don't) under the
+/// same lock that guards the consumer side, so an overflowing buffer can
+/// never panic or deadlock the caller.
+pub struct RingBuffer {
+    events: Mutex<VecDeque<Event>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    not_empty: Condvar,
+    not_full: Condvar,
+    dropped: AtomicUsize,
+    shutdown: Mutex<bool>,
+}
+
+impl RingBuffer {
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> RingBuffer {
+        RingBuffer {
+            events: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity: capacity,
+            policy: policy,
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            dropped: AtomicUsize::new(0),
+            shutdown: Mutex::new(false),
+        }
+    }
+
+    pub fn push(&self, event: Event) {
+        let mut events = self.events.lock().unwrap();
+
+        if events.len() >= self.capacity {
+            match self.policy {
+                OverflowPolicy::DropNewest => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                OverflowPolicy::DropOldest => {
+                    events.pop_front();
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                OverflowPolicy::Block => {
+                    while events.len() >= self.capacity && !*self.shutdown.lock().unwrap() {
+                        events = self.not_full.wait(events).unwrap();
+                    }
+                }
+            }
+        }
+
+        events.push_back(event);
+        self.not_empty.notify_one();
+    }
+
+    /// Drains a batch of queued events, waiting up to `timeout` for the first
+    /// one to arrive if the buffer is currently empty. Once an event has
+    /// arrived, keeps accumulating into the batch until either `max` events
+    /// are collected or `timeout` has elapsed since that first arrival,
+    /// whichever comes first.
+    pub fn drain(&self, max: usize, timeout: Duration) -> Vec<Event> {
+        let mut events = self.events.lock().unwrap();
+
+        if events.is_empty() {
+            let (guard, _) = self.not_empty.wait_timeout(events, timeout).unwrap();
+            events = guard;
+
+            if events.is_empty() {
+                return Vec::new();
+            }
+        }
+
+        let deadline = Instant::now() + timeout;
+
+        while events.len() < max && !self.is_shutdown() {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+
+            let (guard, result) = self.not_empty.wait_timeout(events, deadline - now).unwrap();
+            events = guard;
+
+            if result.timed_out() {
+                break;
+            }
+        }
+
+        let n = cmp::min(events.len(), max);
+        let drained: Vec<Event> = events.drain(..n).collect();
+
+        if n > 0 {
+            self.not_full.notify_all();
+        }
+
+        drained
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.lock().unwrap().is_empty()
+    }
+
+    /// Takes and resets the count of events discarded since the last call.
+    pub fn take_dropped(&self) -> usize {
+        self.dropped.swap(0, Ordering::Relaxed)
+    }
+
+    pub fn shutdown(&self) {
+        *self.shutdown.lock().unwrap() = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        *self.shutdown.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use log::LogLevel;
+
+    use events::Event;
+    use message_templates::MessageTemplate;
+    use super::{OverflowPolicy, RingBuffer};
+
+    fn event(text: &str) -> Event {
+        Event::new_now(LogLevel::Info, MessageTemplate::new(text.to_owned()), BTreeMap::new())
+    }
+
+    #[test]
+    fn drop_oldest_discards_the_front_of_the_queue() {
+        let ring = RingBuffer::new(2, OverflowPolicy::DropOldest);
+
+        ring.push(event("a"));
+        ring.push(event("b"));
+        ring.push(event("c"));
+
+        assert_eq!(1, ring.take_dropped());
+
+        let drained = ring.drain(10, Duration::from_millis(10));
+        let texts: Vec<String> = drained.iter().map(|e| e.message_template().text().to_owned()).collect();
+        assert_eq!(vec!["b".to_owned(), "c".to_owned()], texts);
+    }
+
+    #[test]
+    fn drop_newest_discards_the_incoming_event() {
+        let ring = RingBuffer::new(2, OverflowPolicy::DropNewest);
+
+        ring.push(event("a"));
+        ring.push(event("b"));
+        ring.push(event("c"));
+
+        assert_eq!(1, ring.take_dropped());
+
+        let drained = ring.drain(10, Duration::from_millis(10));
+        let texts: Vec<String> = drained.iter().map(|e| e.message_template().text().to_owned()).collect();
+        assert_eq!(vec!["a".to_owned(), "b".to_owned()], texts);
+    }
+
+    #[test]
+    fn block_waits_for_capacity_instead_of_dropping() {
+        let ring = Arc::new(RingBuffer::new(1, OverflowPolicy::Block));
+        ring.push(event("a"));
+
+        let blocked = ring.clone();
+        let pusher = thread::spawn(move || {
+            blocked.push(event("b"));
+        });
+
+        // Give the pusher a moment to actually block on `not_full` before we
+        // drain; this is inherently timing-based, but a false pass (pusher
+        // finishing instantly) is the only failure mode, not a false fail.
+        thread::sleep(Duration::from_millis(50));
+
+        let drained = ring.drain(1, Duration::from_millis(10));
+        assert_eq!(1, drained.len());
+
+        pusher.join().unwrap();
+        assert_eq!(0, ring.take_dropped());
+
+        let drained = ring.drain(1, Duration::from_millis(10));
+        assert_eq!(1, drained.len());
+    }
+
+    #[test]
+    fn shutdown_wakes_a_pending_drain_with_a_partial_batch() {
+        let ring = Arc::new(RingBuffer::new(10, OverflowPolicy::DropOldest));
+        ring.push(event("a"));
+
+        let draining = ring.clone();
+        let drainer = thread::spawn(move || draining.drain(10, Duration::from_secs(60)));
+
+        // Give the drainer a moment to start waiting for the batch to fill
+        // up to 10 events before we shut it down; a false pass (the drain
+        // returning before shutdown) is the only failure mode, not a false
+        // fail.
+        thread::sleep(Duration::from_millis(50));
+        ring.shutdown();
+
+        let drained = drainer.join().unwrap();
+        assert_eq!(1, drained.len());
+    }
+
+    #[test]
+    fn drain_returns_empty_when_nothing_arrives_before_timeout() {
+        let ring = RingBuffer::new(10, OverflowPolicy::DropOldest);
+        let drained = ring.drain(10, Duration::from_millis(10));
+        assert!(drained.is_empty());
+    }
+}